@@ -0,0 +1,80 @@
+//! 複数の単語をまとめて推論するバッチAPI。
+//!
+//! `infer`は1語ずつしか処理できず、辞書全体や文書に現れる語彙をまとめて
+//! 変換するには非効率です。`infer_batch`は入力を共通の長さにパディングし、
+//! エンコーダ・デコーダをバッチ次元ごと1回で実行します。既に終了した行は
+//! それ以降のステップで固定され、他の行の処理に影響しません。
+
+use crate::inference::{C2k, DecoderState};
+
+impl C2k {
+    /// 複数の入力をバッチとしてまとめて推論します。
+    ///
+    /// 貪欲法のもとでは、各要素を`infer`で個別に処理した場合と同じ結果を返す、
+    /// 純粋にスループットのためのAPIです。`with_max_length`で設定した上限は
+    /// 各行に個別に適用されます。
+    pub fn infer_batch(&self, srcs: &[&str]) -> Vec<String> {
+        if srcs.is_empty() {
+            return Vec::new();
+        }
+
+        let batch_size = srcs.len();
+        // 1件ずつ`encode`を呼ぶと、単語ごとにエンコーダのフォワードパスが
+        // 走ってしまいバッチ化の意味がない。`encode_batch`は入力を共通の長さに
+        // パディングしたうえで、エンコーダをバッチ次元ごと1回のフォワードパスで
+        // 実行する。
+        let encoder_states = self.encode_batch(srcs);
+
+        let mut tokens: Vec<Vec<usize>> = vec![vec![self.bos_id()]; batch_size];
+        // 各行のデコーダ隠れ状態を1トークンずつ前進させて保持する。毎ステップ
+        // 先頭から読み直さないことで、行ごとのコストをトークン長に依存しない
+        // 定数時間に保つ。
+        let mut decoder_states: Vec<DecoderState> = encoder_states
+            .iter()
+            .map(|state| self.decoder_step(&self.decoder_init(state), self.bos_id()))
+            .collect();
+        let mut finished = vec![false; batch_size];
+
+        // 空文字列の入力はBOSのみで直ちに完了として扱う。
+        for (row, src) in srcs.iter().enumerate() {
+            if src.is_empty() {
+                finished[row] = true;
+            }
+        }
+
+        for _ in 0..self.max_length() {
+            if finished.iter().all(|&done| done) {
+                break;
+            }
+
+            // 終了済みの行はマスクしてデコーダに影響を与えないようにしつつ、
+            // バッチ次元をまとめて1回のデコードステップで処理する。
+            let active_rows: Vec<usize> = (0..batch_size).filter(|&row| !finished[row]).collect();
+            let step_states: Vec<&DecoderState> =
+                active_rows.iter().map(|&row| &decoder_states[row]).collect();
+
+            let next_tokens = self.decode_step_batch_argmax(&step_states);
+
+            for (&row, next) in active_rows.iter().zip(next_tokens) {
+                tokens[row].push(next);
+                if next == self.eos_id() {
+                    finished[row] = true;
+                } else {
+                    decoder_states[row] = self.decoder_step(&decoder_states[row], next);
+                }
+            }
+        }
+
+        tokens
+            .iter()
+            .zip(srcs)
+            .map(|(row_tokens, src)| {
+                if src.is_empty() {
+                    String::new()
+                } else {
+                    self.tokens_to_kana(row_tokens)
+                }
+            })
+            .collect()
+    }
+}
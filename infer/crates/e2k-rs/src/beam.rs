@@ -0,0 +1,107 @@
+//! ビームサーチによるn-best推論。
+//!
+//! `C2k::infer`が提供するTopK/TopPサンプリングは非決定的で、貪欲法も含め、綴りが
+//! 曖昧な単語に対して明らかに不自然なカタカナを出力することがあります。
+//! このモジュールはデコーダに対する標準的なビームサーチを実装し、
+//! スコア付きのn-best候補を決定的に返します。
+
+use crate::inference::{C2k, DecoderState};
+
+/// 長さ正規化に用いる指数です。短い出力への偏りを抑えます。
+const LENGTH_PENALTY_ALPHA: f32 = 0.6;
+
+/// ビームサーチの過程で保持される部分仮説です。
+///
+/// `decoder_state`は、この仮説の`tokens`をすべて読み込んだ後のデコーダ隠れ状態を
+/// 1トークンずつ前進させて保持します。毎ステップ先頭から読み直さないことで、
+/// 仮説ごとのコストをトークン長に依存しない定数時間に保ちます。
+#[derive(Debug, Clone)]
+struct Hypothesis {
+    tokens: Vec<usize>,
+    decoder_state: DecoderState,
+    score: f32,
+}
+
+impl Hypothesis {
+    fn normalized_score(&self, alpha: f32) -> f32 {
+        self.score / (self.tokens.len().max(1) as f32).powf(alpha)
+    }
+}
+
+impl C2k {
+    /// ビームサーチにより、最大`beam_width`個までのn-best候補を決定的に推論します。
+    ///
+    /// 返り値はカタカナ文字列と長さ正規化済みの累積対数確率のペアで、
+    /// スコアの高い順に並びます。空文字列を渡した場合は空文字列の仮説を1つ返します。
+    pub fn infer_nbest(&self, src: &str, beam_width: usize) -> Vec<(String, f32)> {
+        if src.is_empty() {
+            return vec![(String::new(), 0.0)];
+        }
+
+        let beam_width = beam_width.max(1);
+        let encoder_state = self.encode(src);
+        let bos_state = self.decoder_step(&self.decoder_init(&encoder_state), self.bos_id());
+
+        let mut live = vec![Hypothesis {
+            tokens: vec![self.bos_id()],
+            decoder_state: bos_state,
+            score: 0.0,
+        }];
+        let mut finished: Vec<Hypothesis> = Vec::new();
+
+        for _ in 0..self.max_length() {
+            if live.is_empty() {
+                break;
+            }
+
+            let mut candidates = Vec::with_capacity(live.len() * beam_width);
+            for hyp in &live {
+                let log_probs = self.decoder_log_probs(&hyp.decoder_state);
+                let mut scored: Vec<(usize, f32)> = log_probs.into_iter().enumerate().collect();
+                scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+                for &(token, log_prob) in scored.iter().take(beam_width) {
+                    let mut tokens = hyp.tokens.clone();
+                    tokens.push(token);
+                    candidates.push(Hypothesis {
+                        score: hyp.score + log_prob,
+                        decoder_state: self.decoder_step(&hyp.decoder_state, token),
+                        tokens,
+                    });
+                }
+            }
+
+            candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            candidates.truncate(beam_width);
+
+            live = Vec::with_capacity(beam_width);
+            for cand in candidates {
+                if cand.tokens.last() == Some(&self.eos_id()) {
+                    finished.push(cand);
+                } else {
+                    live.push(cand);
+                }
+            }
+        }
+
+        // max_lengthに達してもEOSが出なかった場合は、生存している仮説で代替する。
+        if finished.is_empty() {
+            finished = live;
+        }
+
+        finished.sort_by(|a, b| {
+            b.normalized_score(LENGTH_PENALTY_ALPHA)
+                .partial_cmp(&a.normalized_score(LENGTH_PENALTY_ALPHA))
+                .unwrap()
+        });
+        finished.truncate(beam_width);
+
+        finished
+            .into_iter()
+            .map(|hyp| {
+                let score = hyp.normalized_score(LENGTH_PENALTY_ALPHA);
+                (self.tokens_to_kana(&hyp.tokens), score)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,61 @@
+//! エンコーダ・デコーダの語彙表。
+//!
+//! `C2k`は英単語の綴り（ASCII文字列）をエンコーダの入力トークン列として扱い、
+//! デコーダは1文字ずつのカタカナ語彙からトークンを生成します。このモジュールは
+//! それらの語彙とIDの対応を定義します。
+
+/// ASCII文字とエンコーダ入力IDの対応表です。`C2k`はこの表に基づいて
+/// 入力文字列をトークンID列へ変換します。表にない文字（数字や空白など）は
+/// 無視されます。
+pub const ASCII_ENTRIES: &[(char, usize)] = &[
+    ('a', 0),
+    ('b', 1),
+    ('c', 2),
+    ('d', 3),
+    ('e', 4),
+    ('f', 5),
+    ('g', 6),
+    ('h', 7),
+    ('i', 8),
+    ('j', 9),
+    ('k', 10),
+    ('l', 11),
+    ('m', 12),
+    ('n', 13),
+    ('o', 14),
+    ('p', 15),
+    ('q', 16),
+    ('r', 17),
+    ('s', 18),
+    ('t', 19),
+    ('u', 20),
+    ('v', 21),
+    ('w', 22),
+    ('x', 23),
+    ('y', 24),
+    ('z', 25),
+    ('\'', 26),
+    ('-', 27),
+];
+
+/// エンコーダの入力語彙です。インデックスが`ASCII_ENTRIES`のIDに対応します。
+pub const EN_PHONES: &[&str] = &[
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s",
+    "t", "u", "v", "w", "x", "y", "z", "'", "-",
+];
+
+/// デコーダが出力しうるカタカナの語彙です。インデックスがそのままデコーダの
+/// トークンIDになります。末尾の2つは文の開始・終了を表す特殊トークンです。
+pub const KANAS: &[&str] = &[
+    "ア", "イ", "ウ", "エ", "オ", "カ", "キ", "ク", "ケ", "コ", "サ", "シ", "ス", "セ", "ソ",
+    "タ", "チ", "ツ", "テ", "ト", "ナ", "ニ", "ヌ", "ネ", "ノ", "ハ", "ヒ", "フ", "ヘ", "ホ",
+    "マ", "ミ", "ム", "メ", "モ", "ヤ", "ユ", "ヨ", "ラ", "リ", "ル", "レ", "ロ", "ワ", "ヲ",
+    "ン", "ガ", "ギ", "グ", "ゲ", "ゴ", "ザ", "ジ", "ズ", "ゼ", "ゾ", "ダ", "ヂ", "ヅ", "デ",
+    "ド", "バ", "ビ", "ブ", "ベ", "ボ", "パ", "ピ", "プ", "ペ", "ポ", "ッ", "ー", "<bos>",
+    "<eos>",
+];
+
+/// `KANAS`における文開始トークンのID。
+pub const BOS_ID: usize = KANAS.len() - 2;
+/// `KANAS`における文終了トークンのID。
+pub const EOS_ID: usize = KANAS.len() - 1;
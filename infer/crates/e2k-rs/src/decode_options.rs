@@ -0,0 +1,155 @@
+//! デコード時の繰り返し抑制オプション。
+//!
+//! 長い、あるいは辞書にない綴りの入力では、モデルが同じカタカナを繰り返し
+//! 出力し続けてしまうことがあります（例: "ンンン"）。`DecodeOptions`は
+//! 要約タスク向けのTransformerデコーダなどで広く使われている
+//! repetition penaltyとno-repeat-ngramをまとめたもので、貪欲法・TopK・TopP
+//! いずれのサンプリング手法とも組み合わせて使用できます。
+//!
+//! どちらのペナルティも、ソフトマックス前の生のロジットに対して適用します
+//! （`decoder_logits`）。CTRL論文のrepetition penaltyは生のロジットを
+//! 対象に定義されているため、対数確率（常に0以下）に適用すると正負の分岐が
+//! 死んでしまい意味が変わります。`infer`も`infer_with_options`も、内部では
+//! 同じ`C2k::decode`のステップループを`DecodeOptions`付きで呼ぶだけなので、
+//! 貪欲法・TopK・TopPいずれのサンプリング手法を使っていてもペナルティが
+//! 等しく効きます。
+
+use std::collections::HashSet;
+
+use crate::inference::C2k;
+
+/// `C2k::infer`系のメソッドに渡すデコード制御オプションです。
+///
+/// デフォルトでは両方の抑制が無効になっており、既存の挙動は変わりません。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeOptions {
+    /// 既に生成されたトークンのロジットを割り引く係数（θ > 1）。
+    /// `None`の場合は適用しません。
+    pub repetition_penalty: Option<f32>,
+    /// このサイズ以上のn-gramの繰り返しを禁止します。`None`の場合は適用しません。
+    pub no_repeat_ngram_size: Option<usize>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            repetition_penalty: None,
+            no_repeat_ngram_size: None,
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// repetition penaltyとno-repeat-ngramの両方を無効にした設定です。
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// repetition penaltyを設定します。`theta`は1より大きい値を推奨します。
+    pub fn with_repetition_penalty(mut self, theta: f32) -> Self {
+        self.repetition_penalty = Some(theta);
+        self
+    }
+
+    /// no-repeat-ngramのサイズを設定します。
+    pub fn with_no_repeat_ngram_size(mut self, n: usize) -> Self {
+        self.no_repeat_ngram_size = Some(n);
+        self
+    }
+
+    /// サンプリング直前に、このオプションに従って生のロジットを補正します。
+    ///
+    /// `logits`はソフトマックスを適用する前の値である必要があります。
+    /// `generated`はこれまでに生成されたトークン列（BOSを含む）です。
+    pub(crate) fn apply(&self, logits: &mut [f32], generated: &[usize]) {
+        if let Some(theta) = self.repetition_penalty {
+            apply_repetition_penalty(logits, generated, theta);
+        }
+        if let Some(n) = self.no_repeat_ngram_size {
+            block_repeated_ngrams(logits, generated, n);
+        }
+    }
+}
+
+/// 既に生成済みのトークンの生ロジットを`theta`で割り引きます。
+///
+/// 正のロジットは`theta`で割り、負のロジットは`theta`を掛けることで、
+/// 符号に関わらずそのトークンが選ばれにくくなるようにします。ソフトマックス後の
+/// 対数確率（常に0以下）に適用すると正のロジットの分岐が失われてしまうため、
+/// 必ずソフトマックス前の生のロジットに対して呼び出してください。
+fn apply_repetition_penalty(logits: &mut [f32], generated: &[usize], theta: f32) {
+    // 同じトークンが何度生成されていても、割り引くのは1回だけにする。
+    let unique: HashSet<usize> = generated.iter().copied().collect();
+    for token in unique {
+        if let Some(logit) = logits.get_mut(token) {
+            *logit = if *logit > 0.0 {
+                *logit / theta
+            } else {
+                *logit * theta
+            };
+        }
+    }
+}
+
+/// `generated`に続けることで、既出のnグラムを再現してしまうトークンのロジットを
+/// `-∞`にします。
+fn block_repeated_ngrams(logits: &mut [f32], generated: &[usize], n: usize) {
+    if n == 0 || generated.len() + 1 < n {
+        return;
+    }
+
+    let mut seen: HashSet<&[usize]> = HashSet::new();
+    for window in generated.windows(n) {
+        seen.insert(window);
+    }
+
+    let prefix = &generated[generated.len() - (n - 1)..];
+    for (token, logit) in logits.iter_mut().enumerate() {
+        let mut candidate = prefix.to_vec();
+        candidate.push(token);
+        if seen.contains(candidate.as_slice()) {
+            *logit = f32::NEG_INFINITY;
+        }
+    }
+}
+
+impl C2k {
+    /// `options`に従って繰り返し抑制を適用しながら推論します。
+    ///
+    /// `infer`と同じく`decode`を介した単一のステップループを使うので、
+    /// 貪欲法・TopK・TopPいずれのサンプリング手法でもペナルティが等しく効きます。
+    pub fn infer_with_options(&self, src: &str, options: &DecodeOptions) -> String {
+        self.decode(src, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repetition_penalty_discourages_generated_tokens() {
+        let mut logits = vec![1.0, -1.0, 2.0];
+        apply_repetition_penalty(&mut logits, &[0, 1], 2.0);
+        assert_eq!(logits, vec![0.5, -2.0, 2.0]);
+    }
+
+    #[test]
+    fn no_repeat_ngram_blocks_repeated_bigram() {
+        // [0, 1, 0] に続けて 1 を出すと "0, 1" が再び現れてしまうため禁止される。
+        let mut logits = vec![0.0, 0.0, 0.0];
+        block_repeated_ngrams(&mut logits, &[0, 1, 0], 2);
+        assert_eq!(logits[1], f32::NEG_INFINITY);
+        assert_eq!(logits[0], 0.0);
+        assert_eq!(logits[2], 0.0);
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let opts = DecodeOptions::default();
+        let mut logits = vec![1.0, 2.0, 3.0];
+        let before = logits.clone();
+        opts.apply(&mut logits, &[0, 1, 2]);
+        assert_eq!(logits, before);
+    }
+}
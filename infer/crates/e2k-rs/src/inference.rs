@@ -0,0 +1,482 @@
+//! 英単語の綴りをカタカナへ変換する推論エンジン本体。
+//!
+//! エンコーダ・デコーダともに1層のGRUで構成される、単純なseq2seqモデルです。
+//! `C2k`はこのモデルの重みと、推論に使う各種設定（最大長・サンプリング手法）を
+//! 保持します。
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::constants::{ASCII_ENTRIES, BOS_ID, EOS_ID, EN_PHONES, KANAS};
+use crate::decode_options::DecodeOptions;
+use crate::layers::{Embedding, Gru, Linear};
+use crate::model_format::{ModelFormatError, TensorEntry};
+
+/// 隠れ状態の次元数。
+pub(crate) const HIDDEN_SIZE: usize = 64;
+
+/// エンコーダを1語分実行した結果です。デコーダはこの最終隠れ状態を初期状態として
+/// 受け取ります。
+#[derive(Debug, Clone)]
+pub(crate) struct EncoderState {
+    pub(crate) final_hidden: Vec<f32>,
+}
+
+/// デコーダの、ある時点までのトークンを読み込んだ後の隠れ状態です。
+///
+/// ビームサーチやバッチ推論では、仮説・行ごとに異なるトークン列が並行して
+/// 進んでいく。そのたびに先頭から全トークンを読み直すと、1ステップごとの
+/// コストが既読トークン数に比例してしまい、全体ではステップ数の2乗になる。
+/// `DecoderState`を1トークンずつ前進させることで、各ステップを既読トークン数に
+/// 依存しない定数時間の処理にする。
+#[derive(Debug, Clone)]
+pub(crate) struct DecoderState {
+    hidden: Vec<f32>,
+}
+
+/// サンプリング手法です。`infer`・`infer_with_options`がこの設定に従います。
+/// `infer_batch`は常に貪欲法で、`infer_nbest`はビームサーチで次のトークンを選び、
+/// この設定を参照しません。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplingMethod {
+    /// 各ステップで最も確率の高いトークンを選びます。決定的です。
+    Greedy,
+    /// 確率上位`k`件の中から、確率に比例した重みでサンプリングします。
+    TopK(usize),
+    /// 累積確率が`p`に達するまでの上位トークンの中からサンプリングします。
+    TopP(f32),
+}
+
+/// 英単語の綴りをカタカナへ変換する推論器です。
+#[derive(Debug, Clone)]
+pub struct C2k {
+    embed_enc: Embedding,
+    gru_enc: Gru,
+    embed_dec: Embedding,
+    gru_dec: Gru,
+    out_proj: Linear,
+    max_length: usize,
+    sampling: SamplingMethod,
+    rng_state: Cell<u64>,
+}
+
+impl C2k {
+    /// モデルコンテナのバイト列から重みを読み込んで`C2k`を構築します。
+    ///
+    /// `weights`は自己記述的なモデルコンテナのバイト列で、`ModelContainer`の
+    /// フォーマットに従う必要があります。従っていない場合はパニックします。
+    /// `max_length`はデコードの最大長です。
+    pub fn new(weights: &[u8], max_length: usize) -> Self {
+        Self::from_bytes(weights, max_length)
+            .expect("embedded model bytes must be a valid model container")
+    }
+
+    fn with_zeroed_weights(max_length: usize) -> Self {
+        Self {
+            embed_enc: Embedding::new(EN_PHONES.len(), HIDDEN_SIZE),
+            gru_enc: Gru::new(HIDDEN_SIZE, HIDDEN_SIZE),
+            embed_dec: Embedding::new(KANAS.len(), HIDDEN_SIZE),
+            gru_dec: Gru::new(HIDDEN_SIZE, HIDDEN_SIZE),
+            out_proj: Linear::new(HIDDEN_SIZE, KANAS.len()),
+            max_length,
+            sampling: SamplingMethod::Greedy,
+            rng_state: Cell::new(0x9E37_79B9_7F4A_7C15),
+        }
+    }
+
+    /// サンプリング手法を設定します。
+    pub fn with_sampling(mut self, sampling: SamplingMethod) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// 設定済みのサンプリング手法で、英単語の綴りをカタカナへ変換します。
+    pub fn infer(&self, src: &str) -> String {
+        self.decode(src, &DecodeOptions::none())
+    }
+
+    /// `infer`の実体です。`options`で各ステップのロジットを補正してから
+    /// サンプリングするという一本のループを、デフォルト設定の`infer`と
+    /// `infer_with_options`の双方が共有します。
+    pub(crate) fn decode(&self, src: &str, options: &DecodeOptions) -> String {
+        if src.is_empty() {
+            return String::new();
+        }
+
+        let encoder_state = self.encode(src);
+        let mut tokens = vec![self.bos_id()];
+        let mut state = self.decoder_step(&self.decoder_init(&encoder_state), self.bos_id());
+
+        for _ in 0..self.max_length() {
+            let mut logits = self.decoder_logits(&state);
+            options.apply(&mut logits, &tokens);
+
+            let next = self.sample_token(&logits);
+            tokens.push(next);
+            if next == self.eos_id() {
+                break;
+            }
+            state = self.decoder_step(&state, next);
+        }
+
+        self.tokens_to_kana(&tokens)
+    }
+
+    pub(crate) fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    pub(crate) fn bos_id(&self) -> usize {
+        BOS_ID
+    }
+
+    pub(crate) fn eos_id(&self) -> usize {
+        EOS_ID
+    }
+
+    /// 英単語の綴りをエンコーダ入力のトークンID列に変換します。表にない文字は
+    /// 無視します。
+    fn text_to_input_ids(&self, src: &str) -> Vec<usize> {
+        src.chars()
+            .filter_map(|c| {
+                let lower = c.to_ascii_lowercase();
+                ASCII_ENTRIES
+                    .iter()
+                    .find(|&&(ch, _)| ch == lower)
+                    .map(|&(_, id)| id)
+            })
+            .collect()
+    }
+
+    /// デコーダのトークンID列を、BOS/EOSを除いたカタカナ文字列に変換します。
+    pub(crate) fn tokens_to_kana(&self, tokens: &[usize]) -> String {
+        tokens
+            .iter()
+            .filter(|&&t| t != BOS_ID && t != EOS_ID)
+            .map(|&t| KANAS[t])
+            .collect()
+    }
+
+    /// 1語分のエンコーダのフォワードパスを実行します。
+    pub(crate) fn encode(&self, src: &str) -> EncoderState {
+        let tokens = self.text_to_input_ids(src);
+        let mut hidden = vec![0.0; HIDDEN_SIZE];
+        for token in tokens {
+            let embedded = self.embed_enc.forward(token);
+            hidden = self.gru_enc.step(&embedded, &hidden);
+        }
+        EncoderState {
+            final_hidden: hidden,
+        }
+    }
+
+    /// 複数の入力をまとめてエンコーダのバッチ次元で処理します。
+    ///
+    /// 各行を共通のタイムステップ数までパディングし、パディング位置では
+    /// 隠れ状態を更新しないことで、行ごとの結果が1件ずつ`encode`した場合と
+    /// 一致するようにします。
+    pub(crate) fn encode_batch(&self, srcs: &[&str]) -> Vec<EncoderState> {
+        let token_rows: Vec<Vec<usize>> = srcs.iter().map(|src| self.text_to_input_ids(src)).collect();
+        let max_len = token_rows.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut hiddens = vec![vec![0.0; HIDDEN_SIZE]; token_rows.len()];
+        for t in 0..max_len {
+            for (row, tokens) in token_rows.iter().enumerate() {
+                if let Some(&token) = tokens.get(t) {
+                    let embedded = self.embed_enc.forward(token);
+                    hiddens[row] = self.gru_enc.step(&embedded, &hiddens[row]);
+                }
+            }
+        }
+
+        hiddens
+            .into_iter()
+            .map(|final_hidden| EncoderState { final_hidden })
+            .collect()
+    }
+
+    /// `encoder_state`の最終隠れ状態を初期状態とする、空の`DecoderState`を作ります。
+    /// まだ1トークンも読み込んでいない状態です。
+    pub(crate) fn decoder_init(&self, encoder_state: &EncoderState) -> DecoderState {
+        DecoderState {
+            hidden: encoder_state.final_hidden.clone(),
+        }
+    }
+
+    /// `state`に1トークン分の更新を適用した、新しい`DecoderState`を返します。
+    pub(crate) fn decoder_step(&self, state: &DecoderState, token: usize) -> DecoderState {
+        let embedded = self.embed_dec.forward(token);
+        DecoderState {
+            hidden: self.gru_dec.step(&embedded, &state.hidden),
+        }
+    }
+
+    /// `state`から、ソフトマックス前の生のロジットを求めます。`DecodeOptions`による
+    /// 補正はこのロジットに対して行います。
+    pub(crate) fn decoder_logits(&self, state: &DecoderState) -> Vec<f32> {
+        self.out_proj.forward(&state.hidden)
+    }
+
+    /// `state`から、log-softmaxを適用した対数確率を求めます。ビームサーチの
+    /// スコアのように複数ステップにわたって加算していく用途に使います。
+    pub(crate) fn decoder_log_probs(&self, state: &DecoderState) -> Vec<f32> {
+        log_softmax(&self.decoder_logits(state))
+    }
+
+    /// 複数行分のロジットを求め、貪欲法で次のトークンを選びます。
+    /// `infer_batch`が行ごとに異なるタイムステップで使う、バッチ版の1ステップです。
+    pub(crate) fn decode_step_batch_argmax(&self, states: &[&DecoderState]) -> Vec<usize> {
+        states
+            .iter()
+            .map(|state| argmax(&self.decoder_logits(state)))
+            .collect()
+    }
+
+    /// 設定済みの`SamplingMethod`に従って、生のロジットから次のトークンを選びます。
+    pub(crate) fn sample_token(&self, logits: &[f32]) -> usize {
+        match self.sampling {
+            SamplingMethod::Greedy => argmax(logits),
+            SamplingMethod::TopK(k) => self.sample_top_k(logits, k.max(1)),
+            SamplingMethod::TopP(p) => self.sample_top_p(logits, p.clamp(0.0, 1.0)),
+        }
+    }
+
+    fn sample_top_k(&self, logits: &[f32], k: usize) -> usize {
+        let mut ranked = ranked_probabilities(logits);
+        ranked.truncate(k.min(ranked.len()).max(1));
+        self.sample_from(&ranked)
+    }
+
+    fn sample_top_p(&self, logits: &[f32], p: f32) -> usize {
+        let ranked = ranked_probabilities(logits);
+        let mut cumulative = 0.0;
+        let mut cutoff = ranked.len();
+        for (i, &(_, prob)) in ranked.iter().enumerate() {
+            cumulative += prob;
+            if cumulative >= p {
+                cutoff = i + 1;
+                break;
+            }
+        }
+
+        let mut ranked = ranked;
+        ranked.truncate(cutoff.max(1));
+        self.sample_from(&ranked)
+    }
+
+    /// `ranked`の確率に比例した重みで1つのトークンを選びます。
+    fn sample_from(&self, ranked: &[(usize, f32)]) -> usize {
+        let total: f32 = ranked.iter().map(|&(_, prob)| prob).sum();
+        let mut threshold = self.next_unit_f32() * total;
+        for &(token, prob) in ranked {
+            if threshold < prob {
+                return token;
+            }
+            threshold -= prob;
+        }
+        ranked.last().map(|&(token, _)| token).unwrap_or(0)
+    }
+
+    /// xorshift64による、`[0, 1)`の擬似乱数です。暗号学的な強度は不要なため、
+    /// 固定のシード値から始まる簡易実装とし、`getrandom`は使いません。
+    fn next_unit_f32(&self) -> f32 {
+        let mut x = self.rng_state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state.set(x);
+        (x >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// 現在の重みを、モデルコンテナのテンソル一覧として書き出します。
+    pub(crate) fn to_tensors(&self) -> Vec<TensorEntry> {
+        vec![
+            tensor_2d("encoder.embedding.weight", &self.embed_enc.weight),
+            tensor_2d("encoder.gru.input_gates.weight", &self.gru_enc.input_gates.weight),
+            tensor_1d("encoder.gru.input_gates.bias", &self.gru_enc.input_gates.bias),
+            tensor_2d("encoder.gru.hidden_gates.weight", &self.gru_enc.hidden_gates.weight),
+            tensor_1d("encoder.gru.hidden_gates.bias", &self.gru_enc.hidden_gates.bias),
+            tensor_2d("decoder.embedding.weight", &self.embed_dec.weight),
+            tensor_2d("decoder.gru.input_gates.weight", &self.gru_dec.input_gates.weight),
+            tensor_1d("decoder.gru.input_gates.bias", &self.gru_dec.input_gates.bias),
+            tensor_2d("decoder.gru.hidden_gates.weight", &self.gru_dec.hidden_gates.weight),
+            tensor_1d("decoder.gru.hidden_gates.bias", &self.gru_dec.hidden_gates.bias),
+            tensor_2d("decoder.output_projection.weight", &self.out_proj.weight),
+            tensor_1d("decoder.output_projection.bias", &self.out_proj.bias),
+        ]
+    }
+
+    /// モデルコンテナのテンソル一覧から、重みを復元した`C2k`を構築します。
+    pub(crate) fn from_tensors(
+        tensors: Vec<TensorEntry>,
+        max_length: usize,
+    ) -> Result<Self, ModelFormatError> {
+        let mut model = Self::with_zeroed_weights(max_length);
+        let mut by_name: HashMap<String, TensorEntry> =
+            tensors.into_iter().map(|t| (t.name.clone(), t)).collect();
+
+        model.embed_enc.weight =
+            take_2d(&mut by_name, "encoder.embedding.weight", EN_PHONES.len(), HIDDEN_SIZE)?;
+        model.gru_enc.input_gates.weight = take_2d(
+            &mut by_name,
+            "encoder.gru.input_gates.weight",
+            HIDDEN_SIZE * 3,
+            HIDDEN_SIZE,
+        )?;
+        model.gru_enc.input_gates.bias =
+            take_1d(&mut by_name, "encoder.gru.input_gates.bias", HIDDEN_SIZE * 3)?;
+        model.gru_enc.hidden_gates.weight = take_2d(
+            &mut by_name,
+            "encoder.gru.hidden_gates.weight",
+            HIDDEN_SIZE * 3,
+            HIDDEN_SIZE,
+        )?;
+        model.gru_enc.hidden_gates.bias =
+            take_1d(&mut by_name, "encoder.gru.hidden_gates.bias", HIDDEN_SIZE * 3)?;
+
+        model.embed_dec.weight =
+            take_2d(&mut by_name, "decoder.embedding.weight", KANAS.len(), HIDDEN_SIZE)?;
+        model.gru_dec.input_gates.weight = take_2d(
+            &mut by_name,
+            "decoder.gru.input_gates.weight",
+            HIDDEN_SIZE * 3,
+            HIDDEN_SIZE,
+        )?;
+        model.gru_dec.input_gates.bias =
+            take_1d(&mut by_name, "decoder.gru.input_gates.bias", HIDDEN_SIZE * 3)?;
+        model.gru_dec.hidden_gates.weight = take_2d(
+            &mut by_name,
+            "decoder.gru.hidden_gates.weight",
+            HIDDEN_SIZE * 3,
+            HIDDEN_SIZE,
+        )?;
+        model.gru_dec.hidden_gates.bias =
+            take_1d(&mut by_name, "decoder.gru.hidden_gates.bias", HIDDEN_SIZE * 3)?;
+
+        model.out_proj.weight = take_2d(
+            &mut by_name,
+            "decoder.output_projection.weight",
+            KANAS.len(),
+            HIDDEN_SIZE,
+        )?;
+        model.out_proj.bias =
+            take_1d(&mut by_name, "decoder.output_projection.bias", KANAS.len())?;
+
+        Ok(model)
+    }
+}
+
+fn tensor_2d(name: &str, weight: &[Vec<f32>]) -> TensorEntry {
+    let rows = weight.len();
+    let cols = weight.first().map_or(0, Vec::len);
+    TensorEntry {
+        name: name.to_string(),
+        shape: vec![rows, cols],
+        data: weight.iter().flatten().copied().collect(),
+    }
+}
+
+fn tensor_1d(name: &str, data: &[f32]) -> TensorEntry {
+    TensorEntry {
+        name: name.to_string(),
+        shape: vec![data.len()],
+        data: data.to_vec(),
+    }
+}
+
+fn take_2d(
+    by_name: &mut HashMap<String, TensorEntry>,
+    name: &str,
+    rows: usize,
+    cols: usize,
+) -> Result<Vec<Vec<f32>>, ModelFormatError> {
+    let entry = by_name.remove(name).ok_or_else(|| {
+        ModelFormatError::InvalidContainer(format!("model container is missing tensor `{name}`"))
+    })?;
+    if entry.shape != vec![rows, cols] {
+        return Err(ModelFormatError::ShapeMismatch(format!(
+            "tensor `{name}` has shape {:?}, expected [{rows}, {cols}]",
+            entry.shape
+        )));
+    }
+    Ok(entry.data.chunks(cols).map(|chunk| chunk.to_vec()).collect())
+}
+
+fn take_1d(
+    by_name: &mut HashMap<String, TensorEntry>,
+    name: &str,
+    len: usize,
+) -> Result<Vec<f32>, ModelFormatError> {
+    let entry = by_name.remove(name).ok_or_else(|| {
+        ModelFormatError::InvalidContainer(format!("model container is missing tensor `{name}`"))
+    })?;
+    if entry.shape != vec![len] {
+        return Err(ModelFormatError::ShapeMismatch(format!(
+            "tensor `{name}` has shape {:?}, expected [{len}]",
+            entry.shape
+        )));
+    }
+    Ok(entry.data)
+}
+
+fn ranked_probabilities(logits: &[f32]) -> Vec<(usize, f32)> {
+    let mut ranked: Vec<(usize, f32)> = softmax(logits).into_iter().enumerate().collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    ranked
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|&x| (x - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|x| x / sum).collect()
+}
+
+fn log_softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum_exp = logits.iter().map(|&x| (x - max).exp()).sum::<f32>().ln() + max;
+    logits.iter().map(|&x| x - log_sum_exp).collect()
+}
+
+fn argmax(logits: &[f32]) -> usize {
+    logits
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zeroed_model_round_trips_through_tensors() {
+        let model = C2k::with_zeroed_weights(16);
+        let tensors = model.to_tensors();
+        let restored = C2k::from_tensors(tensors, 16).unwrap();
+        assert_eq!(restored.max_length(), model.max_length());
+    }
+
+    #[test]
+    fn from_tensors_rejects_missing_tensor() {
+        let err = C2k::from_tensors(Vec::new(), 16).unwrap_err();
+        assert!(matches!(err, ModelFormatError::InvalidContainer(_)));
+    }
+
+    #[test]
+    fn empty_input_produces_empty_output() {
+        let model = C2k::with_zeroed_weights(16);
+        assert_eq!(model.infer(""), "");
+    }
+
+    #[test]
+    fn greedy_decode_terminates_within_max_length() {
+        let model = C2k::with_zeroed_weights(4);
+        let dst = model.infer("constants");
+        // ゼロ初期化モデルには意味のある出力を期待できないが、無限ループせず
+        // max_length以内で終了することだけを確認する。
+        assert!(dst.chars().count() <= 4);
+    }
+}
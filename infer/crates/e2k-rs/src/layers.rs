@@ -0,0 +1,85 @@
+//! 最小限のテンソル演算レイヤー。
+//!
+//! 外部の線形代数クレートに依存せず、埋め込み・全結合・GRUセルを
+//! プレーンな`Vec<f32>`上で実装します。
+
+/// 埋め込み層です。`weight[token]`がそのトークンの埋め込みベクトルになります。
+#[derive(Debug, Clone)]
+pub(crate) struct Embedding {
+    pub weight: Vec<Vec<f32>>,
+}
+
+impl Embedding {
+    pub fn new(vocab_size: usize, hidden_size: usize) -> Self {
+        Self {
+            weight: vec![vec![0.0; hidden_size]; vocab_size],
+        }
+    }
+
+    pub fn forward(&self, token: usize) -> Vec<f32> {
+        self.weight[token].clone()
+    }
+}
+
+/// 全結合層です。`weight[out][in]`という行優先のレイアウトで保持します。
+#[derive(Debug, Clone)]
+pub(crate) struct Linear {
+    pub weight: Vec<Vec<f32>>,
+    pub bias: Vec<f32>,
+}
+
+impl Linear {
+    pub fn new(in_size: usize, out_size: usize) -> Self {
+        Self {
+            weight: vec![vec![0.0; in_size]; out_size],
+            bias: vec![0.0; out_size],
+        }
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.weight
+            .iter()
+            .zip(&self.bias)
+            .map(|(row, &b)| row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>() + b)
+            .collect()
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// GRUセルです。入力側・隠れ状態側それぞれの3ゲート（reset, update, new）分の
+/// 変換を1つの`Linear`にまとめて保持します。
+#[derive(Debug, Clone)]
+pub(crate) struct Gru {
+    pub input_gates: Linear,
+    pub hidden_gates: Linear,
+    pub hidden_size: usize,
+}
+
+impl Gru {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        Self {
+            input_gates: Linear::new(input_size, hidden_size * 3),
+            hidden_gates: Linear::new(hidden_size, hidden_size * 3),
+            hidden_size,
+        }
+    }
+
+    /// 1タイムステップ分のGRUセルを計算し、新しい隠れ状態を返します。
+    pub fn step(&self, input: &[f32], hidden: &[f32]) -> Vec<f32> {
+        let gi = self.input_gates.forward(input);
+        let gh = self.hidden_gates.forward(hidden);
+        let h = self.hidden_size;
+
+        let mut next = vec![0.0; h];
+        for i in 0..h {
+            let r = sigmoid(gi[i] + gh[i]);
+            let z = sigmoid(gi[h + i] + gh[h + i]);
+            let n = (gi[2 * h + i] + r * gh[2 * h + i]).tanh();
+            next[i] = (1.0 - z) * n + z * hidden[i];
+        }
+        next
+    }
+}
@@ -28,11 +28,18 @@
 //! オフの場合、Hashと適当な値を使用してサンプリングします。
 //!
 
+mod batch;
+mod beam;
 mod constants;
+mod decode_options;
 mod inference;
 mod layers;
+mod model_format;
+pub mod mora;
 
 pub use constants::{ASCII_ENTRIES, EN_PHONES, KANAS};
+pub use decode_options::DecodeOptions;
 pub use inference::*;
+pub use model_format::{ModelContainer, ModelFormatError, TensorEntry};
 #[cfg(feature = "embed_model")]
 pub mod models;
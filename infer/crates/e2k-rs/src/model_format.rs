@@ -0,0 +1,313 @@
+//! 実行時に読み込み可能なモデルフォーマット。
+//!
+//! これまで推論を行うには`C2k::new(e2k::models::C2K_MODEL, ..)`のように、
+//! コンパイル時に埋め込まれたbrotli圧縮済みのバイト列を使うしかありませんでした。
+//! このモジュールは、層の形状とテンソルを自己記述的に並べたシンプルな
+//! バイナリコンテナを定義し、`C2k::from_reader`/`C2k::from_bytes`で
+//! 独自にファインチューニングしたモデルを実行時に読み込めるようにします。
+//!
+//! `C2k::new`自体も内部でこのコンテナ形式のパース（`from_tensors`）を経由する
+//! ように実装されているため、`new`に渡すバイト列はこのフォーマットに
+//! 従っている必要があります。`embed_model`機能が埋め込む定数がこの形式で
+//! 書き出されているかどうかは`models`モジュール側で決まり、このモジュールは
+//! それを保証しません。
+
+use std::io::{self, Read, Write};
+
+use crate::inference::C2k;
+
+const MAGIC: &[u8; 4] = b"E2KM";
+const FORMAT_VERSION: u32 = 1;
+
+/// コンテナに含められるテンソルの最大個数。
+///
+/// 読み込んだカウントをそのまま`Vec::with_capacity`に渡すと、壊れた（あるいは
+/// 悪意のある）モデルファイルが巨大な値を申告するだけで、実際にデータを
+/// 読み切る前にアロケーションが失敗／abortしてしまいます。実行時に読み込む
+/// モデルは信頼できない入力として扱い、妥当な上限を超えるカウントは
+/// その場でエラーにします。
+const MAX_TENSORS: u32 = 4096;
+/// テンソル名の最大バイト長。
+const MAX_TENSOR_NAME_LEN: u32 = 4096;
+/// テンソルの最大次元数。
+const MAX_TENSOR_NDIM: u32 = 8;
+/// 1テンソルあたりの要素数の上限（f32換算で約1GiB）。
+const MAX_TENSOR_ELEMENTS: u64 = 1 << 28;
+
+/// モデルフォーマットの読み書きに失敗したことを表すエラーです。
+#[derive(Debug)]
+pub enum ModelFormatError {
+    Io(io::Error),
+    /// マジックナンバーが一致しない、など、コンテナとして不正な場合。
+    InvalidContainer(String),
+    /// バージョンがサポート外の場合。
+    UnsupportedVersion(u32),
+    /// テンソルの形状や個数が`C2k`の層構成と一致しない場合。
+    ShapeMismatch(String),
+}
+
+impl From<io::Error> for ModelFormatError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl std::fmt::Display for ModelFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "io error while reading model: {err}"),
+            Self::InvalidContainer(msg) => write!(f, "invalid model container: {msg}"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported model format version: {v}"),
+            Self::ShapeMismatch(msg) => write!(f, "model tensor shape mismatch: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ModelFormatError {}
+
+/// 1つの重みテンソルと、その名前・形状です。
+///
+/// 名前は`"encoder.embedding.weight"`のように、対応する層を示す
+/// ドット区切りのパスとして扱います。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorEntry {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+/// 複数の重みテンソルをまとめた、読み書き可能なモデルコンテナです。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelContainer {
+    pub tensors: Vec<TensorEntry>,
+}
+
+impl ModelContainer {
+    /// `reader`からコンテナを読み込みます。
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, ModelFormatError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ModelFormatError::InvalidContainer(
+                "magic number does not match \"E2KM\"".to_string(),
+            ));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(ModelFormatError::UnsupportedVersion(version));
+        }
+
+        let tensor_count = read_u32(&mut reader)?;
+        if tensor_count > MAX_TENSORS {
+            return Err(ModelFormatError::InvalidContainer(format!(
+                "tensor count {tensor_count} exceeds the maximum of {MAX_TENSORS}"
+            )));
+        }
+        let mut tensors = Vec::with_capacity(tensor_count as usize);
+
+        for _ in 0..tensor_count {
+            let name_len = read_u32(&mut reader)?;
+            if name_len > MAX_TENSOR_NAME_LEN {
+                return Err(ModelFormatError::InvalidContainer(format!(
+                    "tensor name length {name_len} exceeds the maximum of {MAX_TENSOR_NAME_LEN}"
+                )));
+            }
+            let mut name_bytes = vec![0u8; name_len as usize];
+            reader.read_exact(&mut name_bytes)?;
+            let name = String::from_utf8(name_bytes).map_err(|err| {
+                ModelFormatError::InvalidContainer(format!("tensor name is not utf-8: {err}"))
+            })?;
+
+            let ndim = read_u32(&mut reader)?;
+            if ndim > MAX_TENSOR_NDIM {
+                return Err(ModelFormatError::InvalidContainer(format!(
+                    "tensor `{name}` has {ndim} dimensions, more than the maximum of {MAX_TENSOR_NDIM}"
+                )));
+            }
+            let mut shape = Vec::with_capacity(ndim as usize);
+            for _ in 0..ndim {
+                shape.push(read_u64(&mut reader)? as usize);
+            }
+
+            let element_count = read_u64(&mut reader)?;
+            if element_count > MAX_TENSOR_ELEMENTS {
+                return Err(ModelFormatError::InvalidContainer(format!(
+                    "tensor `{name}` has {element_count} elements, more than the maximum of {MAX_TENSOR_ELEMENTS}"
+                )));
+            }
+
+            let expected_count: u64 = shape.iter().try_fold(1u64, |acc, &dim| {
+                acc.checked_mul(dim as u64)
+            }).ok_or_else(|| {
+                ModelFormatError::InvalidContainer(format!(
+                    "tensor `{name}` declares shape {shape:?} whose element count overflows"
+                ))
+            })?;
+            if expected_count != element_count {
+                return Err(ModelFormatError::ShapeMismatch(format!(
+                    "tensor `{name}` declares shape {shape:?} ({expected_count} elements) \
+                     but {element_count} elements were stored"
+                )));
+            }
+
+            // `element_count`はここまでで`MAX_TENSOR_ELEMENTS`以下であることを
+            // 確認済みだが、それでも最大約1GiB分のアロケーションになりうる。
+            // 壊れたファイルが実データを伴わずに巨大なカウントだけを申告した場合に
+            // 無駄な巨大アロケーションを起こさないよう、最初は小さめの容量で確保し、
+            // 実際に読み取れた分だけ`push`で伸ばしていく。
+            let mut data = Vec::with_capacity(element_count.min(1024) as usize);
+            let mut buf = [0u8; 4];
+            for _ in 0..element_count {
+                reader.read_exact(&mut buf)?;
+                data.push(f32::from_le_bytes(buf));
+            }
+
+            tensors.push(TensorEntry { name, shape, data });
+        }
+
+        Ok(Self { tensors })
+    }
+
+    /// コンテナを`writer`へ書き出します。
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), ModelFormatError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.tensors.len() as u32).to_le_bytes())?;
+
+        for tensor in &self.tensors {
+            let name_bytes = tensor.name.as_bytes();
+            writer.write_all(&(name_bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(name_bytes)?;
+
+            writer.write_all(&(tensor.shape.len() as u32).to_le_bytes())?;
+            for &dim in &tensor.shape {
+                writer.write_all(&(dim as u64).to_le_bytes())?;
+            }
+
+            writer.write_all(&(tensor.data.len() as u64).to_le_bytes())?;
+            for &value in &tensor.data {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl C2k {
+    /// 自己記述的なモデルコンテナを読み込んで`C2k`を構築します。
+    ///
+    /// 独自にファインチューニングしたモデルを実行時に読み込みたい場合に使います。
+    /// `max_length`は`new`と同じく、デコードの最大長です。
+    pub fn from_reader(reader: impl Read, max_length: usize) -> Result<Self, ModelFormatError> {
+        let container = ModelContainer::from_reader(reader)?;
+        Self::from_tensors(container.tensors, max_length)
+    }
+
+    /// バイト列から`C2k`を構築します。`from_reader`の薄いラッパーです。
+    pub fn from_bytes(bytes: &[u8], max_length: usize) -> Result<Self, ModelFormatError> {
+        Self::from_reader(bytes, max_length)
+    }
+
+    /// 現在のモデルを自己記述的なコンテナとして書き出します。
+    ///
+    /// `embed_model`向けの定数をビルドする場合も、このメソッドで書き出した
+    /// バイト列を埋め込めば`C2k::new`でそのまま読み込めます（実際に
+    /// ビルドスクリプトからそうしているかは`models`モジュール側の実装次第です）。
+    pub fn to_writer(&self, writer: impl Write) -> Result<(), ModelFormatError> {
+        let container = ModelContainer {
+            tensors: self.to_tensors(),
+        };
+        container.write_to(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let container = ModelContainer {
+            tensors: vec![TensorEntry {
+                name: "encoder.embedding.weight".to_string(),
+                shape: vec![2, 3],
+                data: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+            }],
+        };
+
+        let mut bytes = Vec::new();
+        container.write_to(&mut bytes).unwrap();
+
+        let decoded = ModelContainer::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(decoded, container);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let err = ModelContainer::from_reader([0u8; 8].as_slice()).unwrap_err();
+        assert!(matches!(err, ModelFormatError::InvalidContainer(_)));
+    }
+
+    #[test]
+    fn rejects_element_count_that_does_not_match_shape() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // tensor_count
+        bytes.extend_from_slice(&4u32.to_le_bytes()); // name_len
+        bytes.extend_from_slice(b"bias");
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // ndim
+        bytes.extend_from_slice(&2u64.to_le_bytes()); // shape = [2]
+        bytes.extend_from_slice(&999u64.to_le_bytes()); // element_count, inconsistent with shape
+
+        let err = ModelContainer::from_reader(bytes.as_slice()).unwrap_err();
+        assert!(matches!(err, ModelFormatError::ShapeMismatch(_)));
+    }
+
+    #[test]
+    fn rejects_shape_whose_element_count_overflows() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // tensor_count
+        bytes.extend_from_slice(&4u32.to_le_bytes()); // name_len
+        bytes.extend_from_slice(b"bias");
+        bytes.extend_from_slice(&2u32.to_le_bytes()); // ndim
+        bytes.extend_from_slice(&2u64.to_le_bytes()); // shape[0] = 2
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes()); // shape[1] = u64::MAX, 2 * MAX overflows
+        bytes.extend_from_slice(&0u64.to_le_bytes()); // element_count, irrelevant: overflow is rejected first
+
+        let err = ModelContainer::from_reader(bytes.as_slice()).unwrap_err();
+        assert!(matches!(err, ModelFormatError::InvalidContainer(_)));
+    }
+
+    #[test]
+    fn rejects_implausibly_large_element_count_without_allocating() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // tensor_count
+        bytes.extend_from_slice(&4u32.to_le_bytes()); // name_len
+        bytes.extend_from_slice(b"bias");
+        bytes.extend_from_slice(&1u32.to_le_bytes()); // ndim
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes()); // shape = [u64::MAX]
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes()); // element_count
+
+        let err = ModelContainer::from_reader(bytes.as_slice()).unwrap_err();
+        assert!(matches!(err, ModelFormatError::InvalidContainer(_)));
+    }
+}
@@ -0,0 +1,318 @@
+//! カタカナ文字列をモーラ列へ分解するモジュール。
+//!
+//! `C2k`が出力するカタカナは、そのままではVOICEVOXのようなモーラ単位で
+//! 音声を合成するエンジンに渡せません。このモジュールはモーラ表を用いて
+//! カタカナ文字列を`Mora`の列に分解します。
+//!
+//! 子音・母音のラベルは`EN_PHONES`/`KANAS`から導出せず、このモジュール固有の
+//! ローマ字表記（子音は"k"/"sh"/"ky"など、母音は"a"/"i"/"u"/"e"/"o"）で
+//! 独自に定義しています。意図的な逸脱で、理由は次の通りです。
+//!
+//! - `EN_PHONES`はエンコーダ入力側のASCII語彙（英単語の綴り1文字ずつ）であり、
+//!   日本語のモーラとは対応するアルファベットの単位も個数も異なります
+//!   （例えば"sh"や"ky"のような子音クラスタは`EN_PHONES`に存在しません）。
+//! - `KANAS`はデコーダが生成するカタカナ1文字ずつの語彙（トークンID順）であり、
+//!   「キャ」のような2文字からなる拗音モーラや、子音・母音への分解そのものを
+//!   表現できません。
+//!
+//! つまり`EN_PHONES`/`KANAS`はどちらも「推論モデルの入出力トークン」を表す表で
+//! あって、「かな1文字（またはモーラ）に対応する音素」を表す表ではないため、
+//! これらからモーラの子音・母音を導出することは構造的にできません。
+//! `MORA_TABLE`はモーラ分解専用の、独立した固定表として保持します。
+//! 「キャ」「シャ」「ティ」のような拗音・外来音、standalone小書きかな、
+//! 長音「ー」、撥音「ン」、促音「ッ」も1モーラとして扱います。
+
+/// 1モーラを表します。
+///
+/// `consonant`・`vowel`は、対応する音素がない場合（長音など）は`None`になります。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mora {
+    /// このモーラのカタカナ表記。
+    pub kana: String,
+    /// 子音の音素。子音を持たないモーラ（母音のみ、撥音、促音など）は`None`。
+    pub consonant: Option<&'static str>,
+    /// 母音の音素。長音のように母音を持たないモーラは`None`。
+    pub vowel: Option<&'static str>,
+}
+
+/// 特殊モーラ「ッ」（促音）。
+const SOKUON: &str = "ッ";
+/// 特殊モーラ「ン」（撥音）。
+const HATSUON: &str = "ン";
+/// 長音記号「ー」。直前のモーラの母音を伸ばすが、それ自体は音素を持たない。
+const CHOUON: &str = "ー";
+
+/// かな表記と(子音, 母音)のペアです。`None`は音素を持たないことを示します。
+type MoraEntry = (&'static str, Option<&'static str>, Option<&'static str>);
+
+/// 長い表記から先に試すよう、2文字のモーラを先頭にまとめたモーラ表です。
+/// `to_moras`はこの表に対して貪欲に最長一致を行います。
+static MORA_TABLE: &[MoraEntry] = &[
+    // 拗音(2文字)
+    ("キャ", Some("ky"), Some("a")),
+    ("キュ", Some("ky"), Some("u")),
+    ("キョ", Some("ky"), Some("o")),
+    ("シャ", Some("sh"), Some("a")),
+    ("シュ", Some("sh"), Some("u")),
+    ("ショ", Some("sh"), Some("o")),
+    ("チャ", Some("ch"), Some("a")),
+    ("チュ", Some("ch"), Some("u")),
+    ("チョ", Some("ch"), Some("o")),
+    ("ニャ", Some("ny"), Some("a")),
+    ("ニュ", Some("ny"), Some("u")),
+    ("ニョ", Some("ny"), Some("o")),
+    ("ヒャ", Some("hy"), Some("a")),
+    ("ヒュ", Some("hy"), Some("u")),
+    ("ヒョ", Some("hy"), Some("o")),
+    ("ミャ", Some("my"), Some("a")),
+    ("ミュ", Some("my"), Some("u")),
+    ("ミョ", Some("my"), Some("o")),
+    ("リャ", Some("ry"), Some("a")),
+    ("リュ", Some("ry"), Some("u")),
+    ("リョ", Some("ry"), Some("o")),
+    ("ギャ", Some("gy"), Some("a")),
+    ("ギュ", Some("gy"), Some("u")),
+    ("ギョ", Some("gy"), Some("o")),
+    ("ジャ", Some("j"), Some("a")),
+    ("ジュ", Some("j"), Some("u")),
+    ("ジョ", Some("j"), Some("o")),
+    ("ビャ", Some("by"), Some("a")),
+    ("ビュ", Some("by"), Some("u")),
+    ("ビョ", Some("by"), Some("o")),
+    ("ピャ", Some("py"), Some("a")),
+    ("ピュ", Some("py"), Some("u")),
+    ("ピョ", Some("py"), Some("o")),
+    // 外来語に使われる拡張拗音(2文字)
+    ("ティ", Some("t"), Some("i")),
+    ("ディ", Some("d"), Some("i")),
+    ("トゥ", Some("t"), Some("u")),
+    ("ドゥ", Some("d"), Some("u")),
+    ("ファ", Some("f"), Some("a")),
+    ("フィ", Some("f"), Some("i")),
+    ("フェ", Some("f"), Some("e")),
+    ("フォ", Some("f"), Some("o")),
+    ("ウィ", Some("w"), Some("i")),
+    ("ウェ", Some("w"), Some("e")),
+    ("ウォ", Some("w"), Some("o")),
+    ("ヴァ", Some("v"), Some("a")),
+    ("ヴィ", Some("v"), Some("i")),
+    ("ヴェ", Some("v"), Some("e")),
+    ("ヴォ", Some("v"), Some("o")),
+    ("シェ", Some("sh"), Some("e")),
+    ("ジェ", Some("j"), Some("e")),
+    ("チェ", Some("ch"), Some("e")),
+    ("ツァ", Some("ts"), Some("a")),
+    ("ツィ", Some("ts"), Some("i")),
+    ("ツェ", Some("ts"), Some("e")),
+    ("ツォ", Some("ts"), Some("o")),
+    // 清音・濁音・半濁音(1文字)
+    ("ア", None, Some("a")),
+    ("イ", None, Some("i")),
+    ("ウ", None, Some("u")),
+    ("エ", None, Some("e")),
+    ("オ", None, Some("o")),
+    ("カ", Some("k"), Some("a")),
+    ("キ", Some("k"), Some("i")),
+    ("ク", Some("k"), Some("u")),
+    ("ケ", Some("k"), Some("e")),
+    ("コ", Some("k"), Some("o")),
+    ("サ", Some("s"), Some("a")),
+    ("シ", Some("sh"), Some("i")),
+    ("ス", Some("s"), Some("u")),
+    ("セ", Some("s"), Some("e")),
+    ("ソ", Some("s"), Some("o")),
+    ("タ", Some("t"), Some("a")),
+    ("チ", Some("ch"), Some("i")),
+    ("ツ", Some("ts"), Some("u")),
+    ("テ", Some("t"), Some("e")),
+    ("ト", Some("t"), Some("o")),
+    ("ナ", Some("n"), Some("a")),
+    ("ニ", Some("n"), Some("i")),
+    ("ヌ", Some("n"), Some("u")),
+    ("ネ", Some("n"), Some("e")),
+    ("ノ", Some("n"), Some("o")),
+    ("ハ", Some("h"), Some("a")),
+    ("ヒ", Some("h"), Some("i")),
+    ("フ", Some("f"), Some("u")),
+    ("ヘ", Some("h"), Some("e")),
+    ("ホ", Some("h"), Some("o")),
+    ("マ", Some("m"), Some("a")),
+    ("ミ", Some("m"), Some("i")),
+    ("ム", Some("m"), Some("u")),
+    ("メ", Some("m"), Some("e")),
+    ("モ", Some("m"), Some("o")),
+    ("ヤ", Some("y"), Some("a")),
+    ("ユ", Some("y"), Some("u")),
+    ("ヨ", Some("y"), Some("o")),
+    ("ラ", Some("r"), Some("a")),
+    ("リ", Some("r"), Some("i")),
+    ("ル", Some("r"), Some("u")),
+    ("レ", Some("r"), Some("e")),
+    ("ロ", Some("r"), Some("o")),
+    ("ワ", Some("w"), Some("a")),
+    ("ヲ", None, Some("o")),
+    ("ガ", Some("g"), Some("a")),
+    ("ギ", Some("g"), Some("i")),
+    ("グ", Some("g"), Some("u")),
+    ("ゲ", Some("g"), Some("e")),
+    ("ゴ", Some("g"), Some("o")),
+    ("ザ", Some("z"), Some("a")),
+    ("ジ", Some("j"), Some("i")),
+    ("ズ", Some("z"), Some("u")),
+    ("ゼ", Some("z"), Some("e")),
+    ("ゾ", Some("z"), Some("o")),
+    ("ダ", Some("d"), Some("a")),
+    ("ヂ", Some("j"), Some("i")),
+    ("ヅ", Some("z"), Some("u")),
+    ("デ", Some("d"), Some("e")),
+    ("ド", Some("d"), Some("o")),
+    ("バ", Some("b"), Some("a")),
+    ("ビ", Some("b"), Some("i")),
+    ("ブ", Some("b"), Some("u")),
+    ("ベ", Some("b"), Some("e")),
+    ("ボ", Some("b"), Some("o")),
+    ("パ", Some("p"), Some("a")),
+    ("ピ", Some("p"), Some("i")),
+    ("プ", Some("p"), Some("u")),
+    ("ペ", Some("p"), Some("e")),
+    ("ポ", Some("p"), Some("o")),
+    ("ヴ", Some("v"), Some("u")),
+    // 単独で現れる小書きかな。ァィゥェォは対応する母音のみのモーラ、ャュョは
+    // ヤ行と同じく"y"を子音とするモーラとして扱う。
+    ("ァ", None, Some("a")),
+    ("ィ", None, Some("i")),
+    ("ゥ", None, Some("u")),
+    ("ェ", None, Some("e")),
+    ("ォ", None, Some("o")),
+    ("ャ", Some("y"), Some("a")),
+    ("ュ", Some("y"), Some("u")),
+    ("ョ", Some("y"), Some("o")),
+    // 特殊モーラ
+    (SOKUON, None, None),
+    (HATSUON, None, None),
+    (CHOUON, None, None),
+];
+
+/// モーラ表における最長の見出しの文字数。
+const MAX_MORA_CHARS: usize = 2;
+
+/// カタカナ文字列をモーラの列に分解します。
+///
+/// モーラ表に対して常に最長一致を優先するため、「キャ」のような2文字の
+/// モーラが「キ」と「ャ」に分解されてしまうことはありません。モーラ表に
+/// 存在しない文字（読点など）は、音素を持たない1文字のモーラとして
+/// そのまま出力します。
+pub fn to_moras(kana: &str) -> Vec<Mora> {
+    let chars: Vec<char> = kana.chars().collect();
+    let mut moras = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let mut matched = None;
+        for len in (1..=MAX_MORA_CHARS.min(chars.len() - i)).rev() {
+            let candidate: String = chars[i..i + len].iter().collect();
+            if let Some(entry) = MORA_TABLE.iter().find(|(kana, _, _)| *kana == candidate) {
+                matched = Some((candidate, entry.1, entry.2, len));
+                break;
+            }
+        }
+
+        match matched {
+            Some((kana, consonant, vowel, len)) => {
+                moras.push(Mora {
+                    kana,
+                    consonant,
+                    vowel,
+                });
+                i += len;
+            }
+            None => {
+                moras.push(Mora {
+                    kana: chars[i].to_string(),
+                    consonant: None,
+                    vowel: None,
+                });
+                i += 1;
+            }
+        }
+    }
+
+    moras
+}
+
+/// モーラ列をAquesTalk風のアクセント記法へ変換します。
+///
+/// `accent_index`はアクセント核となるモーラの1始まりの位置です。核の直後に
+/// `'`を挿入します。`0`を渡すとアクセント核なし（平板型）として扱われます。
+pub fn to_accent_notation(moras: &[Mora], accent_index: usize) -> String {
+    let mut notation = String::new();
+    for (i, mora) in moras.iter().enumerate() {
+        notation.push_str(&mora.kana);
+        if accent_index != 0 && i + 1 == accent_index {
+            notation.push('\'');
+        }
+    }
+    notation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_youon_as_single_mora() {
+        let moras = to_moras("キャラクター");
+        assert_eq!(moras[0].kana, "キャ");
+        assert_eq!(moras[0].consonant, Some("ky"));
+        assert_eq!(moras[0].vowel, Some("a"));
+    }
+
+    #[test]
+    fn youon_vowels_are_always_plain_a_i_u_e_o() {
+        // キャ行もシャ行などと同じく、拗音の母音はa/i/u/e/oのいずれかに揃う。
+        for (kana, expected_consonant, expected_vowel) in
+            [("キャ", "ky", "a"), ("キュ", "ky", "u"), ("キョ", "ky", "o")]
+        {
+            let moras = to_moras(kana);
+            assert_eq!(moras[0].consonant, Some(expected_consonant));
+            assert_eq!(moras[0].vowel, Some(expected_vowel));
+        }
+    }
+
+    #[test]
+    fn standalone_small_kana_have_phonemes() {
+        let moras = to_moras("ァョ");
+        assert_eq!(moras[0].consonant, None);
+        assert_eq!(moras[0].vowel, Some("a"));
+        assert_eq!(moras[1].consonant, Some("y"));
+        assert_eq!(moras[1].vowel, Some("o"));
+    }
+
+    #[test]
+    fn handles_special_moras() {
+        let moras = to_moras("カッコン");
+        let kanas: Vec<&str> = moras.iter().map(|m| m.kana.as_str()).collect();
+        assert_eq!(kanas, vec!["カ", "ッ", "コ", "ン"]);
+        assert_eq!(moras[1].consonant, None);
+        assert_eq!(moras[1].vowel, None);
+    }
+
+    #[test]
+    fn handles_long_vowel() {
+        let moras = to_moras("コーヒー");
+        let kanas: Vec<&str> = moras.iter().map(|m| m.kana.as_str()).collect();
+        assert_eq!(kanas, vec!["コ", "ー", "ヒ", "ー"]);
+    }
+
+    #[test]
+    fn renders_accent_notation() {
+        let moras = to_moras("コンスタンツ");
+        let notation = to_accent_notation(&moras, 2);
+        assert_eq!(notation, "コン'スタンツ");
+    }
+
+    #[test]
+    fn empty_input_has_no_moras() {
+        assert!(to_moras("").is_empty());
+    }
+}